@@ -3,14 +3,10 @@ use crate::msg::DataFrame;
 use crate::runtime;
 use bytes::Bytes;
 use flume::{Receiver, Sender};
-use governor::{Quota, RateLimiter};
 use smol::prelude::*;
+use std::collections::{HashMap, VecDeque};
 use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
-use std::time::Duration;
-use std::{
-    collections::{HashMap, HashSet, VecDeque},
-    num::NonZeroU32,
-};
+use std::time::{Duration, Instant};
 
 async fn infal<T, E, F: Future<Output = std::result::Result<T, E>>>(fut: F) -> T {
     match fut.await {
@@ -29,6 +25,10 @@ pub struct SessionConfig {
     pub recv_frame: Receiver<DataFrame>,
 }
 
+/// A reasonable `target_throughput` for `Session::new` when the caller has
+/// no better estimate of the link's steady-state bandwidth, in bytes/sec.
+pub const DEFAULT_TARGET_THROUGHPUT: u32 = 1_000_000;
+
 /// Representation of an isolated session that deals only in DataFrames and abstracts away all I/O concerns. It's the user's responsibility to poll the session. Otherwise, it might not make progress and will drop packets.
 pub struct Session {
     send_tosend: Sender<Bytes>,
@@ -40,11 +40,22 @@ pub struct Session {
 
 impl Session {
     /// Creates a tuple of a Session and also a channel with which stuff is fed into the session.
-    pub fn new(cfg: SessionConfig) -> Self {
+    ///
+    /// `target_throughput`, in bytes/sec, seeds the sender's pacing rate
+    /// before any loss samples are available; it's then widened/narrowed as
+    /// measured loss moves. Pass `DEFAULT_TARGET_THROUGHPUT` if the caller
+    /// has no better estimate.
+    pub fn new(cfg: SessionConfig, target_throughput: u32) -> Self {
         let (send_tosend, recv_tosend) = flume::bounded(1000);
         let (send_input, recv_input) = flume::bounded(1000);
         let (s, r) = flume::unbounded();
-        let task = runtime::spawn(session_loop(cfg, recv_tosend, send_input, r));
+        let task = runtime::spawn(session_loop(
+            cfg,
+            target_throughput,
+            recv_tosend,
+            send_input,
+            r,
+        ));
         Session {
             send_tosend,
             recv_input,
@@ -86,10 +97,113 @@ pub struct SessionStats {
     pub down_loss: f64,
     pub down_recovered_loss: f64,
     pub down_redundant: f64,
+    pub up_bytes_per_sec: f64,
+    pub up_max_bytes_per_sec: f64,
+    pub down_bytes_per_sec: f64,
+    pub down_max_bytes_per_sec: f64,
+}
+
+/// How far back a `ByteRateTracker` looks when averaging throughput.
+const RATE_WINDOW: Duration = Duration::from_secs(5);
+
+/// Below this much wall-clock span, `ByteRateTracker::bytes_per_sec` reports
+/// zero rather than dividing by a near-instantaneous span -- otherwise the
+/// very first frame of a session (a single sample, span ~0) divides by a
+/// floor instead of real elapsed time and reports a huge, meaningless
+/// instantaneous rate that then permanently poisons `peak_bytes_per_sec`,
+/// which only ever ratchets upward.
+const MIN_RATE_SPAN: Duration = Duration::from_millis(250);
+
+/// A rolling sample table of (bytes, timestamp) pairs for one direction of
+/// traffic, used to report live throughput without keeping every frame ever
+/// seen.
+struct ByteRateTracker {
+    samples: VecDeque<(Instant, u64)>,
+    windowed_bytes: u64,
+    peak_bytes_per_sec: f64,
+}
+
+impl ByteRateTracker {
+    fn new() -> Self {
+        ByteRateTracker {
+            samples: VecDeque::new(),
+            windowed_bytes: 0,
+            peak_bytes_per_sec: 0.0,
+        }
+    }
+
+    fn record(&mut self, bytes: u64) {
+        let now = Instant::now();
+        self.samples.push_back((now, bytes));
+        self.windowed_bytes += bytes;
+        while let Some((ts, sample_bytes)) = self.samples.front() {
+            if now.saturating_duration_since(*ts) > RATE_WINDOW {
+                self.windowed_bytes -= sample_bytes;
+                self.samples.pop_front();
+            } else {
+                break;
+            }
+        }
+        let rate = self.bytes_per_sec();
+        if rate > self.peak_bytes_per_sec {
+            self.peak_bytes_per_sec = rate;
+        }
+    }
+
+    fn bytes_per_sec(&self) -> f64 {
+        match self.samples.front() {
+            Some((oldest, _)) => {
+                let span = Instant::now().saturating_duration_since(*oldest);
+                if span < MIN_RATE_SPAN {
+                    return 0.0;
+                }
+                self.windowed_bytes as f64 / span.as_secs_f64()
+            }
+            None => 0.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod byte_rate_tracker_tests {
+    use super::*;
+    use std::thread::sleep;
+
+    #[test]
+    fn a_single_sample_does_not_report_an_instantaneous_rate() {
+        let mut tracker = ByteRateTracker::new();
+        tracker.record(1400);
+        // one sample spans ~0 wall-clock time -- must not divide by a floor
+        // and report a bogus rate, nor let it poison the peak.
+        assert_eq!(tracker.bytes_per_sec(), 0.0);
+        assert_eq!(tracker.peak_bytes_per_sec, 0.0);
+    }
+
+    #[test]
+    fn sustained_traffic_reports_a_rate_close_to_the_real_one() {
+        let mut tracker = ByteRateTracker::new();
+        // ~100KB/s: 1400 bytes every 14ms, for long enough to clear MIN_RATE_SPAN
+        for _ in 0..30 {
+            tracker.record(1400);
+            sleep(Duration::from_millis(14));
+        }
+        let rate = tracker.bytes_per_sec();
+        assert!(
+            rate > 50_000.0 && rate < 150_000.0,
+            "rate {} should be in the ballpark of the real ~100KB/s stream, not a startup artifact",
+            rate
+        );
+        assert!(
+            tracker.peak_bytes_per_sec < 150_000.0,
+            "peak {} should not have been poisoned by an early single-sample spike",
+            tracker.peak_bytes_per_sec
+        );
+    }
 }
 
 async fn session_loop(
     cfg: SessionConfig,
+    target_throughput: u32,
     recv_tosend: Receiver<Bytes>,
     send_input: Sender<Bytes>,
     recv_statreq: Receiver<Sender<SessionStats>>,
@@ -97,14 +211,16 @@ async fn session_loop(
     let measured_loss = AtomicU8::new(0);
     let high_recv_frame_no = AtomicU64::new(0);
     let total_recv_frames = AtomicU64::new(0);
+    let up_rate = smol::lock::Mutex::new(ByteRateTracker::new());
+    let down_rate = smol::lock::Mutex::new(ByteRateTracker::new());
 
     // sending loop
     let send_loop = async {
-        let shaper = RateLimiter::direct_with_clock(
-            Quota::per_second(NonZeroU32::new(10000u32).unwrap())
-                .allow_burst(NonZeroU32::new(128).unwrap()),
-            &governor::clock::MonotonicClock::default(),
-        );
+        // Paced from the caller-supplied `target_throughput`, then
+        // widened/narrowed as `measured_loss` moves, so that a FEC run's
+        // shards (especially the trailing parity ones) are spread across
+        // time instead of dumped on the wire in one burst.
+        let mut pacer = Pacer::new(target_throughput);
         let mut frame_no = 0u64;
         let mut run_no = 0u64;
         let mut to_send = Vec::new();
@@ -131,8 +247,10 @@ async fn session_loop(
                 }
             };
             // encode into raptor
-            let encoded = FrameEncoder::new(loss_to_u8(cfg.target_loss))
-                .encode(measured_loss.load(Ordering::Relaxed), &to_send);
+            let loss = measured_loss.load(Ordering::Relaxed);
+            let encoded = FrameEncoder::new(loss_to_u8(cfg.target_loss)).encode(loss, &to_send);
+            pacer.update_loss(loss);
+            let data_shards = to_send.len();
             for (idx, bts) in encoded.iter().enumerate() {
                 if frame_no % 1000 == 0 {
                     log::debug!(
@@ -141,21 +259,24 @@ async fn session_loop(
                         measured_loss.load(Ordering::Relaxed)
                     );
                 }
+                if frame_no > 0 {
+                    smol::Timer::after(pacer.gap(bts.len(), idx >= data_shards)).await;
+                }
+                up_rate.lock().await.record(bts.len() as u64);
                 drop(
                     cfg.send_frame
                         .send_async(DataFrame {
                             frame_no,
                             run_no,
                             run_idx: idx as u8,
-                            data_shards: to_send.len() as u8,
-                            parity_shards: (encoded.len() - to_send.len()) as u8,
+                            data_shards: data_shards as u8,
+                            parity_shards: (encoded.len() - data_shards) as u8,
                             high_recv_frame_no: high_recv_frame_no.load(Ordering::Relaxed),
                             total_recv_frames: total_recv_frames.load(Ordering::Relaxed),
                             body: bts.clone(),
                         })
                         .await,
                 );
-                // shaper.until_ready().await;
                 frame_no += 1;
             }
             run_no += 1;
@@ -175,6 +296,7 @@ async fn session_loop(
                 );
                 continue;
             }
+            down_rate.lock().await.record(new_frame.body.len() as u64);
             loss_calc.update_params(new_frame.high_recv_frame_no, new_frame.total_recv_frames);
             measured_loss.store(loss_to_u8(loss_calc.median), Ordering::Relaxed);
             high_recv_frame_no.fetch_max(new_frame.frame_no, Ordering::Relaxed);
@@ -197,6 +319,8 @@ async fn session_loop(
         loop {
             let req = infal(recv_statreq.recv_async()).await;
             let decoder = decoder.lock().await;
+            let up_rate = up_rate.lock().await;
+            let down_rate = down_rate.lock().await;
             let response = SessionStats {
                 down_total: high_recv_frame_no.load(Ordering::Relaxed),
                 down_loss: 1.0
@@ -207,6 +331,10 @@ async fn session_loop(
                     - (decoder.correct_count as f64 / decoder.total_count as f64).min(1.0),
                 down_redundant: decoder.total_parity_shards as f64
                     / decoder.total_data_shards as f64,
+                up_bytes_per_sec: up_rate.bytes_per_sec(),
+                up_max_bytes_per_sec: up_rate.peak_bytes_per_sec,
+                down_bytes_per_sec: down_rate.bytes_per_sec(),
+                down_max_bytes_per_sec: down_rate.peak_bytes_per_sec,
             };
             infal(req.send_async(response)).await;
         }
@@ -270,41 +398,155 @@ impl RunDecoder {
     }
 }
 
-/// A filter for replays. Records recently seen seqnos and rejects either repeats or really old seqnos.
+/// Number of `u64` words in the replay bitmap. The sliding window covers the
+/// word holding the top seqno and the `WORDS - 1` words before it, so it
+/// remembers between `(WORDS - 1) * 64 + 1` and `WORDS * 64` recent seqnos.
+const REPLAY_FILTER_WORDS: usize = 64;
+const REPLAY_FILTER_BITS: u64 = (REPLAY_FILTER_WORDS * 64) as u64;
+
+/// A filter for replays, backed by an RFC 6479-style sliding-window bitmap
+/// instead of a `HashSet`: O(1) per packet and zero allocation on advance,
+/// at the cost of a fixed-size window of recently seen seqnos.
 #[derive(Debug)]
 struct ReplayFilter {
     top_seqno: u64,
-    bottom_seqno: u64,
-    seen_seqno: HashSet<u64>,
+    bitmap: [u64; REPLAY_FILTER_WORDS],
 }
 
 impl ReplayFilter {
     fn new(start: u64) -> Self {
         ReplayFilter {
             top_seqno: start,
-            bottom_seqno: start,
-            seen_seqno: HashSet::new(),
+            bitmap: [0u64; REPLAY_FILTER_WORDS],
         }
     }
 
+    fn word_index(&self, seqno: u64) -> usize {
+        ((seqno / 64) % REPLAY_FILTER_WORDS as u64) as usize
+    }
+
     fn add(&mut self, seqno: u64) -> bool {
-        if seqno < self.bottom_seqno {
-            // out of range. we can't know, so we just say no
+        if seqno - seqno % 64 + REPLAY_FILTER_BITS <= self.top_seqno - self.top_seqno % 64 {
+            // too old to be represented in the window at all: its word's
+            // slot in the bitmap has been reused by a newer word
             return false;
         }
-        // check the seen
-        if self.seen_seqno.contains(&seqno) {
-            return false;
+        if seqno > self.top_seqno {
+            // Slide the window forward, zeroing only the *words* that have
+            // newly rotated into view -- not one zero per seqno advanced,
+            // which would also wipe out still-valid bits that happen to
+            // share a word with the new top (e.g. advancing from 64 to 66
+            // must not clear the bit for 64 itself, since 64 and 66 share
+            // word index 1).
+            let old_word = self.top_seqno / 64;
+            let new_word = seqno / 64;
+            let words_to_clear = (new_word - old_word).min(REPLAY_FILTER_WORDS as u64);
+            for i in 1..=words_to_clear {
+                let idx = ((old_word + i) % REPLAY_FILTER_WORDS as u64) as usize;
+                self.bitmap[idx] = 0;
+            }
+            self.top_seqno = seqno;
         }
-        self.top_seqno = seqno;
-        while self.top_seqno - self.bottom_seqno > 1000 {
-            self.seen_seqno.remove(&self.bottom_seqno);
-            self.bottom_seqno += 1;
+        let idx = self.word_index(seqno);
+        let bit = 1u64 << (seqno % 64);
+        if self.bitmap[idx] & bit != 0 {
+            return false;
         }
+        self.bitmap[idx] |= bit;
         true
     }
 }
 
+#[cfg(test)]
+mod replay_filter_tests {
+    use super::ReplayFilter;
+
+    #[test]
+    fn accepts_increasing_seqnos() {
+        let mut rf = ReplayFilter::new(0);
+        assert!(rf.add(0));
+        assert!(rf.add(1));
+        assert!(rf.add(2));
+    }
+
+    #[test]
+    fn rejects_exact_replay() {
+        let mut rf = ReplayFilter::new(0);
+        assert!(rf.add(5));
+        assert!(!rf.add(5));
+    }
+
+    #[test]
+    fn rejects_replay_after_sliding_within_the_same_word() {
+        // regression test: 64 and 66 share a bitmap word (index 1). Sliding
+        // the top forward to 66 must not clear the bit already set for 64.
+        let mut rf = ReplayFilter::new(0);
+        assert!(rf.add(64));
+        assert!(rf.add(65));
+        assert!(rf.add(66));
+        assert!(!rf.add(64), "64 was already accepted and must not replay");
+        assert!(!rf.add(65), "65 was already accepted and must not replay");
+    }
+
+    #[test]
+    fn rejects_seqnos_older_than_the_window() {
+        let mut rf = ReplayFilter::new(0);
+        assert!(rf.add(10_000));
+        assert!(!rf.add(0));
+    }
+
+    #[test]
+    fn large_jump_clears_the_whole_window() {
+        let mut rf = ReplayFilter::new(0);
+        assert!(rf.add(0));
+        assert!(rf.add(100_000));
+        // 0 is long gone, but a fresh seqno within the new window accepts
+        assert!(rf.add(100_001));
+        assert!(!rf.add(100_001));
+    }
+}
+
+/// Spaces outgoing frames out over time instead of bursting a whole FEC run
+/// at once. The base rate is the caller-supplied `target_throughput` (see
+/// `Session::new`), narrowed as measured loss rises (backing off the link);
+/// parity shards get an extra widening so they land spread across the run
+/// rather than clumped with the data shards they're meant to protect.
+///
+/// `session_loop` has no handle on the `mux::relconn::ConnVars` of the
+/// connections it's carrying frames for, so this can't yet be re-seeded from
+/// `ConnVars::pacing_rate()` the way the congestion layer intends; it falls
+/// back to the configured target plus loss-based narrowing until a
+/// connection handle is threaded through.
+struct Pacer {
+    target_throughput: f64,
+    loss: f64,
+}
+
+impl Pacer {
+    fn new(target_throughput: u32) -> Self {
+        Pacer {
+            target_throughput: target_throughput as f64,
+            loss: 0.0,
+        }
+    }
+
+    fn update_loss(&mut self, measured_loss: u8) {
+        self.loss = measured_loss as f64 / 255.0;
+    }
+
+    /// The gap to wait before sending a frame of `body_len` bytes.
+    fn gap(&self, body_len: usize, is_parity: bool) -> Duration {
+        // back off the pacing rate as loss rises, so that a lossy link gets
+        // slower, wider-spaced frames rather than a single fast burst
+        let rate = (self.target_throughput * (1.0 - self.loss)).max(1024.0);
+        let mut secs = body_len as f64 / rate;
+        if is_parity {
+            secs *= 1.0 + self.loss;
+        }
+        Duration::from_secs_f64(secs)
+    }
+}
+
 fn loss_to_u8(loss: f64) -> u8 {
     let loss = loss * 256.0;
     if loss > 254.0 {